@@ -0,0 +1,189 @@
+// Command-line handling: which device to open (by default, or by an
+// explicit `usb:VID/PID[:iface]` selector), which config/interface/endpoint
+// to use instead of letting `find_readable_endpoint` guess, and the
+// `--list` enumeration mode.
+extern crate rusb;
+
+use std::time::Duration;
+
+use rusb::{Context, UsbContext};
+
+const DEFAULT_VID: u16 = 9408;
+const DEFAULT_PID: u16 = 3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceSelector {
+    pub vid: u16,
+    pub pid: u16,
+    pub iface: Option<u8>
+}
+
+impl Default for DeviceSelector {
+    fn default() -> DeviceSelector {
+        DeviceSelector { vid: DEFAULT_VID, pid: DEFAULT_PID, iface: None }
+    }
+}
+
+/// Pins `find_readable_endpoint` to a specific config/interface/endpoint
+/// instead of letting it grab the first IN endpoint it finds.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EndpointOverride {
+    pub config: Option<u8>,
+    pub iface: Option<u8>,
+    pub address: Option<u8>
+}
+
+/// Which `Sink` decoded readings are emitted through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Human,
+    JsonLines
+}
+
+pub struct Args {
+    pub device: DeviceSelector,
+    pub endpoint: EndpointOverride,
+    pub list: bool,
+    pub serve_usbip: bool,
+    pub replay: Option<String>,
+    pub format: OutputFormat
+}
+
+pub fn parse(args: &[String]) -> Args {
+    let mut device = DeviceSelector::default();
+    let mut endpoint = EndpointOverride::default();
+    let mut list = false;
+    let mut serve_usbip = false;
+    let mut replay = None;
+    let mut format = OutputFormat::Human;
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--list" => list = true,
+            "--serve-usbip" => serve_usbip = true,
+            "--replay" => {
+                replay = args.get(i + 1).cloned();
+                i += 1;
+            },
+            "--format" => {
+                format = match args.get(i + 1).map(|s| s.as_str()) {
+                    Some("json") => OutputFormat::JsonLines,
+                    Some("human") => OutputFormat::Human,
+                    _ => format
+                };
+                i += 1;
+            },
+            "--device" => {
+                if let Some(selector) = args.get(i + 1).and_then(|s| parse_device_selector(s)) {
+                    device = selector;
+                }
+                i += 1;
+            },
+            "--config" => {
+                endpoint.config = args.get(i + 1).and_then(|s| s.parse().ok());
+                i += 1;
+            },
+            "--interface" => {
+                endpoint.iface = args.get(i + 1).and_then(|s| s.parse().ok());
+                i += 1;
+            },
+            "--endpoint" => {
+                endpoint.address = args.get(i + 1).and_then(|s| parse_hex_u8(s));
+                i += 1;
+            },
+            _ => ()
+        }
+
+        i += 1;
+    }
+
+    Args { device, endpoint, list, serve_usbip, replay, format }
+}
+
+/// Parses the `usb:%04x/%04x` device-name convention, with an optional
+/// `:interface` suffix, e.g. `usb:24c0/0003` or `usb:24c0/0003:0`.
+fn parse_device_selector(s: &str) -> Option<DeviceSelector> {
+    let rest = s.strip_prefix("usb:")?;
+
+    let mut parts = rest.splitn(2, '/');
+    let vid = u16::from_str_radix(parts.next()?, 16).ok()?;
+    let remainder = parts.next()?;
+
+    let mut remainder_parts = remainder.splitn(2, ':');
+    let pid = u16::from_str_radix(remainder_parts.next()?, 16).ok()?;
+    let iface = match remainder_parts.next() {
+        Some(iface) => iface.parse().ok(),
+        None => None
+    };
+
+    Some(DeviceSelector { vid, pid, iface })
+}
+
+fn parse_hex_u8(s: &str) -> Option<u8> {
+    let s = s.trim_start_matches("0x");
+    u8::from_str_radix(s, 16).ok()
+}
+
+/// Enumerates every attached USB device, printing enough to build a
+/// `--device`/`--config`/`--interface`/`--endpoint` selection from.
+pub fn list_devices(context: &Context) {
+    let devices = match context.devices() {
+        Ok(d) => d,
+        Err(e) => {
+            println!("could not enumerate devices: {}", e);
+            return;
+        }
+    };
+
+    for device in devices.iter() {
+        let device_desc = match device.device_descriptor() {
+            Ok(d) => d,
+            Err(_) => continue
+        };
+
+        println!("usb:{:04x}/{:04x} (bus {} device {})", device_desc.vendor_id(), device_desc.product_id(), device.bus_number(), device.address());
+
+        print_strings(&device, &device_desc);
+
+        for n in 0..device_desc.num_configurations() {
+            let config_desc = match device.config_descriptor(n) {
+                Ok(c) => c,
+                Err(_) => continue
+            };
+
+            for interface in config_desc.interfaces() {
+                for interface_desc in interface.descriptors() {
+                    for endpoint_desc in interface_desc.endpoint_descriptors() {
+                        println!("  config {} interface {} setting {} endpoint {:#04x} {:?}",
+                            config_desc.number(),
+                            interface_desc.interface_number(),
+                            interface_desc.setting_number(),
+                            endpoint_desc.address(),
+                            endpoint_desc.direction());
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn print_strings(device: &rusb::Device<Context>, device_desc: &rusb::DeviceDescriptor) {
+    let timeout = Duration::from_secs(1);
+
+    let handle = match device.open() {
+        Ok(handle) => handle,
+        Err(_) => return
+    };
+
+    let languages = match handle.read_languages(timeout) {
+        Ok(languages) => languages,
+        Err(_) => return
+    };
+
+    if let Some(&language) = languages.first() {
+        println!("  manufacturer: {:?}", handle.read_manufacturer_string(language, device_desc, timeout).ok());
+        println!("  product: {:?}", handle.read_product_string(language, device_desc, timeout).ok());
+        println!("  serial: {:?}", handle.read_serial_number_string(language, device_desc, timeout).ok());
+    }
+}
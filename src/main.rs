@@ -1,107 +1,248 @@
-extern crate libusb;
-
-use std::slice;
+extern crate env_logger;
+#[macro_use]
+extern crate log;
+extern crate rusb;
+
+mod cli;
+mod decoder;
+mod report_source;
+mod sink;
+mod usbip;
+
+use std::env;
+use std::sync::mpsc;
 use std::time::Duration;
 use std::thread;
 
+use rusb::{Context, Device, DeviceHandle, Hotplug, HotplugBuilder, UsbContext};
+
+use cli::{DeviceSelector, EndpointOverride, OutputFormat};
+use report_source::{FileReportSource, LiveReportSource, ReportSource, SourceError};
+use sink::{HumanSink, JsonLinesSink, Sink};
+use usbip::{ExportedDevice, LibusbInterfaceHandler};
+
 #[derive(Debug)]
 struct Endpoint {
     config: u8,
     iface: u8,
     setting: u8,
+    #[allow(dead_code)]
     address: u8
 }
 
-static LIBUSB_REQUEST_TYPE_CLASS: u8 = (0x01 << 5);
-static LIBUSB_RECIPIENT_INTERFACE: u8 = 0x01;
-static LIBUSB_ENDPOINT_IN: u8 = 0x80;
-static REPORT_ONE: u16 = 0x01;
-static REPORT_TWO: u16 = 0x02;
-static READ_REQUEST: u8 = 0x01;
-static READ_VALUE: u16 = 0x0100;
-static READ_INDEX: u16 = 0x00;
+/// Sent from the hotplug callback (which runs on libusb's event thread) to
+/// the supervisor loop, which owns the device handle and does the actual work.
+enum DeviceEvent {
+    Arrived(Device<Context>),
+    Left
+}
+
+struct HotplugHandler {
+    tx: mpsc::Sender<DeviceEvent>
+}
+
+impl Hotplug<Context> for HotplugHandler {
+    fn device_arrived(&mut self, device: Device<Context>) {
+        self.tx.send(DeviceEvent::Arrived(device)).ok();
+    }
+
+    fn device_left(&mut self, _device: Device<Context>) {
+        self.tx.send(DeviceEvent::Left).ok();
+    }
+}
 
 fn main() {
-    let vid: u16 = 9408;
-    let pid: u16 = 3;
-
-    match libusb::Context::new() {
-        Ok(mut context) => {
-            match open_device(&mut context, vid, pid) {
-                Some((mut device, device_desc, mut handle)) => read_device(&mut device, &device_desc, &mut handle).unwrap(),
-                None => println!("could not find device {:04x}:{:04x}", vid, pid)
+    env_logger::init();
+
+    let args: Vec<String> = env::args().collect();
+    let parsed = cli::parse(&args);
+
+    let mut out_sink = make_sink(parsed.format);
+
+    if let Some(path) = parsed.replay {
+        run_replay(&path, &mut *out_sink);
+        return;
+    }
+
+    let context = match Context::new() {
+        Ok(context) => context,
+        Err(e) => panic!("could not initialize libusb: {}", e)
+    };
+
+    if parsed.list {
+        cli::list_devices(&context);
+        return;
+    }
+
+    if let Err(e) = run_supervisor(context, parsed.device, parsed.endpoint, parsed.serve_usbip, &mut *out_sink) {
+        panic!("supervisor exited: {}", e);
+    }
+}
+
+fn make_sink(format: OutputFormat) -> Box<dyn Sink> {
+    match format {
+        OutputFormat::Human => Box::new(HumanSink),
+        OutputFormat::JsonLines => Box::new(JsonLinesSink)
+    }
+}
+
+/// Feeds a captured trace through the same decode-and-output path as a live
+/// device, so field bugs can be reproduced without an Acurite bridge attached.
+fn run_replay(path: &str, out_sink: &mut dyn Sink) {
+    match FileReportSource::open(path) {
+        Ok(mut source) => {
+            if let Err(e) = read_loop(&mut source, out_sink) {
+                println!("replay stopped: {}", e);
             }
         },
-        Err(e) => panic!("could not initialize libusb: {}", e)
+        Err(e) => println!("could not open replay file {}: {}", path, e)
     }
 }
 
-fn open_device(context: &mut libusb::Context, vid: u16, pid: u16) -> Option<(libusb::Device, libusb::DeviceDescriptor, libusb::DeviceHandle)> {
-    let devices = match context.devices() {
+/// Waits for the Acurite station to show up, drives it until it is unplugged
+/// or a transfer fails fatally, then goes back to waiting for the next
+/// arrival instead of leaving the process stuck on a one-shot open.
+fn run_supervisor(context: Context, device: DeviceSelector, endpoint: EndpointOverride, serve_usbip: bool, out_sink: &mut dyn Sink) -> rusb::Result<()> {
+    let (tx, rx) = mpsc::channel();
+
+    let _registration = HotplugBuilder::new()
+        .vendor_id(device.vid)
+        .product_id(device.pid)
+        .enumerate(true)
+        .register(context.clone(), Box::new(HotplugHandler { tx }))?;
+
+    let event_context = context.clone();
+    thread::spawn(move || {
+        loop {
+            event_context.handle_events(Some(Duration::from_secs(1))).ok();
+        }
+    });
+
+    let mut endpoint = endpoint;
+    if endpoint.iface.is_none() {
+        endpoint.iface = device.iface;
+    }
+
+    loop {
+        match rx.recv() {
+            Ok(DeviceEvent::Arrived(mut usb_device)) => {
+                match open_device_handle(&mut usb_device) {
+                    Some((device_desc, mut handle)) => {
+                        println!("device {:04x}:{:04x} arrived", device.vid, device.pid);
+
+                        if serve_usbip {
+                            serve_usbip_device(&mut usb_device, &device_desc, &mut handle);
+                        } else if let Err(e) = read_device(&mut usb_device, &device_desc, &mut handle, &endpoint, out_sink) {
+                            warn!("device error, waiting for reconnect: {}", e);
+                        }
+                    },
+                    None => println!("could not open device {:04x}:{:04x}", device.vid, device.pid)
+                }
+            },
+            Ok(DeviceEvent::Left) => println!("device {:04x}:{:04x} departed", device.vid, device.pid),
+            Err(_) => return Ok(())
+        }
+    }
+}
+
+fn open_device_handle(device: &mut Device<Context>) -> Option<(rusb::DeviceDescriptor, DeviceHandle<Context>)> {
+    let device_desc = match device.device_descriptor() {
         Ok(d) => d,
         Err(_) => return None
     };
 
-    for device in devices.iter() {
-        let device_desc = match device.device_descriptor() {
-            Ok(d) => d,
-            Err(_) => continue
-        };
+    match device.open() {
+        Ok(handle) => Some((device_desc, handle)),
+        Err(_) => None
+    }
+}
 
-        if device_desc.vendor_id() == vid && device_desc.product_id() == pid {
-            match device.open() {
-                Ok(handle) => return Some((device, device_desc, handle)),
-                Err(_) => continue
-            }
+/// Re-exports the already-located device over USB/IP instead of decoding
+/// reports locally, so a remote host can attach it and do the decoding.
+fn serve_usbip_device(device: &mut Device<Context>, device_desc: &rusb::DeviceDescriptor, handle: &mut DeviceHandle<Context>) {
+    let exported = match ExportedDevice::from_device(device, device_desc) {
+        Some(exported) => exported,
+        None => {
+            println!("No readable control endpoint to export");
+            return;
         }
-    }
+    };
 
-    None
+    let timeout = Duration::from_secs(30);
+    let handler = Box::new(LibusbInterfaceHandler::new(handle, timeout));
+
+    if let Err(err) = usbip::serve(exported, handler) {
+        println!("usbip: server error: {}", err);
+    }
 }
 
-fn read_device(device: &mut libusb::Device, device_desc: &libusb::DeviceDescriptor, handle: &mut libusb::DeviceHandle) -> libusb::Result<()> {
-    try!(handle.reset());
+fn read_device(device: &mut Device<Context>, device_desc: &rusb::DeviceDescriptor, handle: &mut DeviceHandle<Context>, endpoint_override: &EndpointOverride, out_sink: &mut dyn Sink) -> Result<(), SourceError> {
+    handle.reset()?;
 
     let timeout = Duration::from_secs(1);
-    let languages = try!(handle.read_languages(timeout));
+    let languages = handle.read_languages(timeout)?;
 
-    println!("Active configuration: {}", try!(handle.active_configuration()));
-    println!("Languages: {:?}", languages);
+    debug!("Active configuration: {}", handle.active_configuration()?);
+    debug!("Languages: {:?}", languages);
 
-    if languages.len() > 0 {
+    if !languages.is_empty() {
         let language = languages[0];
 
-        println!("Manufacturer: {:?}", handle.read_manufacturer_string(language, device_desc, timeout).ok());
-        println!("Product: {:?}", handle.read_product_string(language, device_desc, timeout).ok());
-        println!("Serial Number: {:?}", handle.read_serial_number_string(language, device_desc, timeout).ok());
+        debug!("Manufacturer: {:?}", handle.read_manufacturer_string(language, device_desc, timeout).ok());
+        debug!("Product: {:?}", handle.read_product_string(language, device_desc, timeout).ok());
+        debug!("Serial Number: {:?}", handle.read_serial_number_string(language, device_desc, timeout).ok());
     }
 
-    match find_readable_endpoint(device, device_desc) {
-        Some(endpoint) => read_endpoint(handle, endpoint),
-        None => println!("No readable control endpoint")
+    match find_readable_endpoint(device, device_desc, endpoint_override) {
+        Some(endpoint) => read_endpoint(handle, endpoint, out_sink),
+        None => {
+            warn!("No readable control endpoint");
+            Ok(())
+        }
     }
-
-    Ok(())
 }
 
-fn find_readable_endpoint(device: &mut libusb::Device, device_desc: &libusb::DeviceDescriptor) -> Option<Endpoint> {
+/// Picks the first IN endpoint that satisfies the override (config,
+/// interface and/or exact endpoint address left unset fall back to "any"),
+/// rather than always grabbing the very first one on the device.
+fn find_readable_endpoint(device: &mut Device<Context>, device_desc: &rusb::DeviceDescriptor, endpoint_override: &EndpointOverride) -> Option<Endpoint> {
     for n in 0..device_desc.num_configurations() {
         let config_desc = match device.config_descriptor(n) {
             Ok(c) => c,
             Err(_) => continue
         };
 
+        if let Some(config) = endpoint_override.config {
+            if config_desc.number() != config {
+                continue;
+            }
+        }
+
         for interface in config_desc.interfaces() {
             for interface_desc in interface.descriptors() {
+                if let Some(iface) = endpoint_override.iface {
+                    if interface_desc.interface_number() != iface {
+                        continue;
+                    }
+                }
+
                 for endpoint_desc in interface_desc.endpoint_descriptors() {
-                    if endpoint_desc.direction() == libusb::Direction::In {
-                        return Some(Endpoint {
-                            config: config_desc.number(),
-                            iface: interface_desc.interface_number(),
-                            setting: interface_desc.setting_number(),
-                            address: endpoint_desc.address()
-                        });
+                    if endpoint_desc.direction() != rusb::Direction::In {
+                        continue;
+                    }
+
+                    if let Some(address) = endpoint_override.address {
+                        if endpoint_desc.address() != address {
+                            continue;
+                        }
                     }
+
+                    return Some(Endpoint {
+                        config: config_desc.number(),
+                        iface: interface_desc.interface_number(),
+                        setting: interface_desc.setting_number(),
+                        address: endpoint_desc.address()
+                    });
                 }
             }
         }
@@ -110,8 +251,8 @@ fn find_readable_endpoint(device: &mut libusb::Device, device_desc: &libusb::Dev
     None
 }
 
-fn read_endpoint(handle: &mut libusb::DeviceHandle, endpoint: Endpoint) {
-    println!("Reading from endpoint: {:?}", endpoint);
+fn read_endpoint(handle: &mut DeviceHandle<Context>, endpoint: Endpoint, out_sink: &mut dyn Sink) -> Result<(), SourceError> {
+    debug!("Reading from endpoint: {:?}", endpoint);
 
     let has_kernel_driver = match handle.kernel_driver_active(endpoint.iface) {
         Ok(true) => {
@@ -121,86 +262,43 @@ fn read_endpoint(handle: &mut libusb::DeviceHandle, endpoint: Endpoint) {
         _ => false
     };
 
-    println!(" - kernel driver? {}", has_kernel_driver);
+    debug!(" - kernel driver? {}", has_kernel_driver);
 
-    match configure_endpoint(handle, &endpoint) {
+    let result = match configure_endpoint(handle, &endpoint) {
         Ok(_) => {
-            let mut vec = Vec::<u8>::with_capacity(256);
-            let mut buf = unsafe { slice::from_raw_parts_mut((&mut vec[..]).as_mut_ptr(), vec.capacity()) };
-
-            let timeout = Duration::from_secs(30);
-            let mut counter: u64 = 0;
-            loop {
-                thread::sleep(Duration::from_millis(1000));
-                
-                /* Fetch REPORT_ONE */
-                if counter % 10 == 0 {
-                    match handle.read_control(
-                        LIBUSB_REQUEST_TYPE_CLASS | LIBUSB_RECIPIENT_INTERFACE | LIBUSB_ENDPOINT_IN,
-                        0x01,
-                        0x0100 + REPORT_ONE,
-                        0,
-                        buf,
-                        timeout) {
-                        Ok(len) => {
-                            unsafe { vec.set_len(len) };
-
-                            if (vec[3] & 0x0f) == 1 {
-                                let wind_speed: f32 = (((vec[4] & 0x1f) << 3) | ((vec[5] & 0x70) >> 7)) as f32 * 0.62;
-                                let wind_dir: u8 = vec[5] & 0x0f;
-                                let rain_count: u8 = vec[7] & 0x7f;
-
-                                println!("wind speed: {:?} wind dir: {:?} rain count: {:?}", wind_speed, wind_dir, rain_count);
-                            }
-
-                            if (vec[3] & 0x0f) == 8 {
-                                let wind_speed: f32 = (((vec[4] & 0x1f) << 3) | ((vec[5] & 0x70) >> 7)) as f32 * 0.62;
-                                let temp: f32 = ((((vec[5] & 0x0f) >> 7) | (vec[6] & 0x7f)) as f32 - 400.00) / 10.0;
-                                let humidity: u8 = vec[7] & 0x7f;
-
-                                println!("wind speed: {:?} temp: {:?} humidity: {:?}", wind_speed, temp, humidity);
-                            }
-                        },
-                        Err(err) => println!("could not read from endpoint: {}", err)
-                    }
-                }
-
-                /* Fetch REPORT_TWO */
-                if counter % 30 == 0 {
-                    match handle.read_control(
-                        LIBUSB_REQUEST_TYPE_CLASS | LIBUSB_RECIPIENT_INTERFACE | LIBUSB_ENDPOINT_IN,
-                        READ_REQUEST,
-                        READ_VALUE + REPORT_TWO,
-                        READ_INDEX,
-                        buf,
-                        timeout) {
-                        Ok(len) => {
-                            unsafe { vec.set_len(len) };
-                            /*println!(" - read: {:?}", vec); */
-                        },
-                        Err(err) => println!("could not read from endpoint: {}", err)
-                    }
-                }
-
-                /* Show latest data
-                if counter % 15 == 0 {
-                    println!("TODO: Output buffer here");
-                } */
-
-                counter = counter + 1;
-            }
+            let mut source = LiveReportSource::new(handle, Duration::from_secs(30));
+            read_loop(&mut source, out_sink)
         },
-        Err(err) => println!("could not configure endpoint: {}", err)
-    }
+        Err(err) => {
+            warn!("could not configure endpoint: {}", err);
+            Err(SourceError::from(err))
+        }
+    };
 
     if has_kernel_driver {
         handle.attach_kernel_driver(endpoint.iface).ok();
     }
+
+    result
+}
+
+/// Decodes and emits whatever the source produces through the selected sink,
+/// until the source is exhausted (replay) or fails fatally (device gone), at
+/// which point the caller tears things down and, for a live device, the
+/// supervisor waits for reconnect.
+fn read_loop(source: &mut dyn ReportSource, out_sink: &mut dyn Sink) -> Result<(), SourceError> {
+    loop {
+        let report = source.next_report()?;
+
+        if let Some(reading) = decoder::decode_report_one(&report) {
+            out_sink.emit(&reading);
+        }
+    }
 }
 
-fn configure_endpoint<'a>(handle: &'a mut libusb::DeviceHandle, endpoint: &Endpoint) -> libusb::Result<()> {
-    try!(handle.set_active_configuration(endpoint.config));
-    try!(handle.claim_interface(endpoint.iface));
-    try!(handle.set_alternate_setting(endpoint.iface, endpoint.setting));
+fn configure_endpoint(handle: &mut DeviceHandle<Context>, endpoint: &Endpoint) -> rusb::Result<()> {
+    handle.set_active_configuration(endpoint.config)?;
+    handle.claim_interface(endpoint.iface)?;
+    handle.set_alternate_setting(endpoint.iface, endpoint.setting)?;
     Ok(())
 }
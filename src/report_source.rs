@@ -0,0 +1,190 @@
+// Abstracts "where do the raw HID report frames come from" so the decode
+// loop can run against a live device or a captured trace without caring
+// which one it is.
+extern crate rusb;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::thread;
+use std::time::Duration;
+
+use rusb::{Context, DeviceHandle};
+
+const LIBUSB_REQUEST_TYPE_CLASS: u8 = 0x01 << 5;
+const LIBUSB_RECIPIENT_INTERFACE: u8 = 0x01;
+const LIBUSB_ENDPOINT_IN: u8 = 0x80;
+const REPORT_ONE: u16 = 0x01;
+const REPORT_TWO: u16 = 0x02;
+const READ_REQUEST: u8 = 0x01;
+const READ_VALUE: u16 = 0x0100;
+const READ_INDEX: u16 = 0x00;
+
+#[derive(Debug)]
+pub enum SourceError {
+    Usb(rusb::Error),
+    Io(io::Error),
+    /// The replay file ran out of frames.
+    Eof
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SourceError::Usb(ref e) => write!(f, "{}", e),
+            SourceError::Io(ref e) => write!(f, "{}", e),
+            SourceError::Eof => write!(f, "end of replay file")
+        }
+    }
+}
+
+impl From<rusb::Error> for SourceError {
+    fn from(e: rusb::Error) -> SourceError {
+        SourceError::Usb(e)
+    }
+}
+
+impl From<io::Error> for SourceError {
+    fn from(e: io::Error) -> SourceError {
+        SourceError::Io(e)
+    }
+}
+
+/// Yields raw report frames one at a time, whatever is behind them.
+pub trait ReportSource {
+    fn next_report(&mut self) -> Result<Vec<u8>, SourceError>;
+}
+
+/// Pulls REPORT_ONE/REPORT_TWO frames from the live device, one frame per
+/// due one-second tick: REPORT_TWO every 30th tick, REPORT_ONE on every
+/// other 10th tick (so no REPORT_ONE is read on a REPORT_TWO tick).
+pub struct LiveReportSource<'a> {
+    handle: &'a mut DeviceHandle<Context>,
+    timeout: Duration,
+    counter: u64
+}
+
+impl<'a> LiveReportSource<'a> {
+    pub fn new(handle: &'a mut DeviceHandle<Context>, timeout: Duration) -> LiveReportSource<'a> {
+        LiveReportSource { handle, timeout, counter: 0 }
+    }
+
+    fn read_report(&mut self, request: u8, value: u16, index: u16) -> Result<Vec<u8>, SourceError> {
+        let mut buf = vec![0u8; 256];
+        let len = self.handle.read_control(
+            LIBUSB_REQUEST_TYPE_CLASS | LIBUSB_RECIPIENT_INTERFACE | LIBUSB_ENDPOINT_IN,
+            request,
+            value,
+            index,
+            &mut buf,
+            self.timeout)?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
+
+impl<'a> ReportSource for LiveReportSource<'a> {
+    fn next_report(&mut self) -> Result<Vec<u8>, SourceError> {
+        loop {
+            thread::sleep(Duration::from_millis(1000));
+            self.counter += 1;
+
+            let due = if self.counter.is_multiple_of(30) {
+                Some((READ_REQUEST, READ_VALUE + REPORT_TWO, READ_INDEX))
+            } else if self.counter.is_multiple_of(10) {
+                Some((0x01, 0x0100 + REPORT_ONE, 0))
+            } else {
+                None
+            };
+
+            let (request, value, index) = match due {
+                Some(due) => due,
+                None => continue
+            };
+
+            match self.read_report(request, value, index) {
+                Ok(report) => return Ok(report),
+                Err(SourceError::Usb(rusb::Error::NoDevice)) => return Err(SourceError::Usb(rusb::Error::NoDevice)),
+                Err(err) => warn!("could not read from endpoint: {}", err)
+            }
+        }
+    }
+}
+
+/// Replays frames captured from a real station so the decoder can be driven
+/// without owning an Acurite bridge. Each line is a hex-encoded frame,
+/// optionally prefixed with a millisecond timestamp (`<ts> <hex>`) used to
+/// pace replay the way the frames were originally spaced.
+pub struct FileReportSource {
+    frames: Vec<(Option<u64>, Vec<u8>)>,
+    index: usize,
+    last_timestamp_ms: Option<u64>
+}
+
+impl FileReportSource {
+    pub fn open(path: &str) -> io::Result<FileReportSource> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut frames = Vec::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let first = parts.next().unwrap();
+
+            let (timestamp_ms, hex) = match parts.next() {
+                Some(hex) => (first.parse::<u64>().ok(), hex),
+                None => (None, first)
+            };
+
+            let bytes = decode_hex(hex).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "malformed hex frame"))?;
+            frames.push((timestamp_ms, bytes));
+        }
+
+        Ok(FileReportSource { frames, index: 0, last_timestamp_ms: None })
+    }
+}
+
+impl ReportSource for FileReportSource {
+    fn next_report(&mut self) -> Result<Vec<u8>, SourceError> {
+        if self.index >= self.frames.len() {
+            return Err(SourceError::Eof);
+        }
+
+        let (timestamp_ms, bytes) = self.frames[self.index].clone();
+        self.index += 1;
+
+        if let (Some(ts), Some(last)) = (timestamp_ms, self.last_timestamp_ms) {
+            if ts > last {
+                thread::sleep(Duration::from_millis(ts - last));
+            }
+        }
+
+        if timestamp_ms.is_some() {
+            self.last_timestamp_ms = timestamp_ms;
+        }
+
+        Ok(bytes)
+    }
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, ()> {
+    if !s.len().is_multiple_of(2) {
+        return Err(());
+    }
+
+    let chars: Vec<char> = s.chars().collect();
+    let mut bytes = Vec::with_capacity(s.len() / 2);
+
+    for pair in chars.chunks(2) {
+        let byte_str: String = pair.iter().collect();
+        bytes.push(u8::from_str_radix(&byte_str, 16).map_err(|_| ())?);
+    }
+
+    Ok(bytes)
+}
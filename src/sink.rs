@@ -0,0 +1,41 @@
+// Where decoded readings go. Splitting this from the decode loop means a
+// reading can be handed to a human-readable line or a JSON-lines record for
+// downstream tooling (collectd, a logger, a database importer).
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::decoder::Reading;
+
+pub trait Sink {
+    fn emit(&mut self, reading: &Reading);
+}
+
+/// The original `println!` formatting, kept as the default sink.
+pub struct HumanSink;
+
+impl Sink for HumanSink {
+    fn emit(&mut self, reading: &Reading) {
+        match *reading {
+            Reading::WindRainTemp { wind_kmh, wind_dir, rain_count } =>
+                println!("wind speed: {:?} wind dir: {:?} rain count: {:?}", wind_kmh, wind_dir, rain_count),
+            Reading::WindTempHumidity { wind_kmh, temp_c, humidity } =>
+                println!("wind speed: {:?} temp: {:?} humidity: {:?}", wind_kmh, temp_c, humidity)
+        }
+    }
+}
+
+/// One `{"ts":...}` JSON object per line, so readings can be piped straight
+/// into a log shipper or database importer.
+pub struct JsonLinesSink;
+
+impl Sink for JsonLinesSink {
+    fn emit(&mut self, reading: &Reading) {
+        let ts = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+        match *reading {
+            Reading::WindRainTemp { wind_kmh, wind_dir, rain_count } =>
+                println!("{{\"ts\":{},\"wind_kmh\":{},\"wind_dir\":{},\"rain_count\":{}}}", ts, wind_kmh, wind_dir, rain_count),
+            Reading::WindTempHumidity { wind_kmh, temp_c, humidity } =>
+                println!("{{\"ts\":{},\"wind_kmh\":{},\"temp_c\":{},\"humidity\":{}}}", ts, wind_kmh, temp_c, humidity)
+        }
+    }
+}